@@ -0,0 +1,68 @@
+/* LICENSE BEGIN
+    This file is part of the SixtyFPS Project -- https://sixtyfps.io
+    Copyright (c) 2020 Olivier Goffart <olivier.goffart@sixtyfps.io>
+    Copyright (c) 2020 Simon Hausmann <simon.hausmann@sixtyfps.io>
+
+    SPDX-License-Identifier: GPL-3.0-only
+    This file is also available under commercial licensing terms.
+    Please contact info@sixtyfps.io for more information.
+LICENSE END */
+
+//! `textDocument/codeLens`: a "Show Preview" (and, for `Window`-based components, "Set as live
+//! preview") lens above every top-level component, so users don't have to put the cursor on the
+//! component and open the code-action menu just to preview it.
+
+use crate::{component_is_window, DocumentCache, SHOW_PREVIEW_COMMAND};
+use lsp_types::{CodeLens, Command, TextDocumentIdentifier};
+use sixtyfps_compilerlib::parser::{identifier_text, syntax_nodes};
+
+pub fn code_lens(
+    document_cache: &mut DocumentCache,
+    text_document: TextDocumentIdentifier,
+) -> Option<Vec<CodeLens>> {
+    let uri = text_document.uri;
+    let path = uri.to_file_path().ok()?;
+    let line_index = document_cache.line_indices.get(&uri)?.clone();
+    let doc = document_cache.documents.get_document(&path)?;
+    let node = doc.node.as_ref()?;
+
+    let mut lenses = Vec::new();
+    for component in node.children().filter_map(syntax_nodes::Component::new) {
+        // A component being typed can have a malformed/missing identifier; skip just that one
+        // component rather than aborting the whole request and losing the lenses already
+        // collected for every other component in the document.
+        let name = match identifier_text(&component.DeclaredIdentifier()) {
+            Some(name) => name,
+            None => continue,
+        };
+        let ident_range = component.DeclaredIdentifier().text_range();
+        let range = lsp_types::Range::new(
+            line_index.offset_to_position(ident_range.start().into()),
+            line_index.offset_to_position(ident_range.end().into()),
+        );
+        let args = Some(vec![path.to_string_lossy().into(), name.clone().into()]);
+
+        lenses.push(CodeLens {
+            range,
+            command: Some(Command::new(
+                "▶ Show Preview".into(),
+                SHOW_PREVIEW_COMMAND.into(),
+                args.clone(),
+            )),
+            data: None,
+        });
+
+        if component_is_window(document_cache, &path, &name) {
+            lenses.push(CodeLens {
+                range,
+                command: Some(Command::new(
+                    "Set as live preview".into(),
+                    SHOW_PREVIEW_COMMAND.into(),
+                    args,
+                )),
+                data: None,
+            });
+        }
+    }
+    Some(lenses)
+}
@@ -0,0 +1,76 @@
+/* LICENSE BEGIN
+    This file is part of the SixtyFPS Project -- https://sixtyfps.io
+    Copyright (c) 2020 Olivier Goffart <olivier.goffart@sixtyfps.io>
+    Copyright (c) 2020 Simon Hausmann <simon.hausmann@sixtyfps.io>
+
+    SPDX-License-Identifier: GPL-3.0-only
+    This file is also available under commercial licensing terms.
+    Please contact info@sixtyfps.io for more information.
+LICENSE END */
+
+//! Support for cancelling read-only requests (completion, goto, hover, ...) - either explicitly
+//! via `$/cancelRequest`, or implicitly when a newer edit to the same document supersedes them.
+//!
+//! These requests all run synchronously on the main message loop thread rather than on a worker
+//! pool: `DocumentCache` holds a `TypeLoader`, whose cached documents are rowan syntax trees and
+//! `langtype::Type`s built on `Rc<RefCell<_>>`, none of which is `Send`. There's no sound way to
+//! hand a `DocumentCache` (or a clone of one - cloning an `Rc` doesn't change what it points to)
+//! to a real OS thread, so there's no pool to hand work off to; cancellation is purely
+//! cooperative book-keeping between requests that run one at a time, not preemption of one by
+//! another. Since each request is registered, computed and completed before the next message is
+//! read, a `$/cancelRequest` or an edit never finds a request still in flight to cancel.
+
+use lsp_server::RequestId;
+use lsp_types::Url;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+
+/// A cooperative cancellation flag shared between a request's entry in [`RequestTracker`] and the
+/// code computing its response, which checks `is_cancelled` to answer `ContentModified` instead.
+#[derive(Clone, Default)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::SeqCst)
+    }
+}
+
+/// Tracks requests currently being computed, keyed by their `RequestId`, so they
+/// can be cancelled by id (`$/cancelRequest`) or by document (a newer edit supersedes any
+/// in-flight request against the old contents of that document).
+#[derive(Default)]
+pub struct RequestTracker {
+    in_flight: Mutex<HashMap<RequestId, (Url, CancellationToken)>>,
+}
+
+impl RequestTracker {
+    pub fn register(&self, id: RequestId, uri: Url) -> CancellationToken {
+        let token = CancellationToken::default();
+        self.in_flight.lock().unwrap().insert(id, (uri, token.clone()));
+        token
+    }
+
+    pub fn complete(&self, id: &RequestId) {
+        self.in_flight.lock().unwrap().remove(id);
+    }
+
+    pub fn cancel(&self, id: &RequestId) {
+        if let Some((_, token)) = self.in_flight.lock().unwrap().get(id) {
+            token.cancel();
+        }
+    }
+
+    pub fn cancel_requests_for_document(&self, uri: &Url) {
+        for (doc_uri, token) in self.in_flight.lock().unwrap().values() {
+            if doc_uri == uri {
+                token.cancel();
+            }
+        }
+    }
+}
@@ -0,0 +1,193 @@
+/* LICENSE BEGIN
+    This file is part of the SixtyFPS Project -- https://sixtyfps.io
+    Copyright (c) 2020 Olivier Goffart <olivier.goffart@sixtyfps.io>
+    Copyright (c) 2020 Simon Hausmann <simon.hausmann@sixtyfps.io>
+
+    SPDX-License-Identifier: GPL-3.0-only
+    This file is also available under commercial licensing terms.
+    Please contact info@sixtyfps.io for more information.
+LICENSE END */
+
+//! `textDocument/formatting` and `textDocument/rangeFormatting`: re-indent and normalize
+//! whitespace in a `.slint` document by walking its rowan green tree token by token, rather than
+//! re-printing from the (lossy) AST, so comments survive unchanged; only whitespace is rewritten.
+
+use crate::DocumentCache;
+use lsp_types::{Position, Range, TextDocumentIdentifier, TextEdit};
+use sixtyfps_compilerlib::parser::{SyntaxKind, SyntaxNode};
+
+/// Number of spaces per nesting depth.
+const INDENT: &str = "  ";
+
+pub fn format_document(
+    document_cache: &mut DocumentCache,
+    text_document: TextDocumentIdentifier,
+) -> Option<Vec<TextEdit>> {
+    let uri = text_document.uri;
+    let path = uri.to_file_path().ok()?;
+    let line_index = document_cache.line_indices.get(&uri)?.clone();
+    let source = document_cache.source_code.get(&uri)?.clone();
+    let doc = document_cache.documents.get_document(&path)?;
+    let node = doc.node.as_ref()?;
+
+    if has_syntax_error(node) {
+        // The parser is error-resilient, but re-flowing whitespace and braces around a syntax
+        // error risks mangling a file the user is still in the middle of typing. No-op instead.
+        return None;
+    }
+
+    let formatted = format_node(node);
+    if formatted == source {
+        return Some(Vec::new());
+    }
+    let end = line_index.offset_to_position(source.len() as u32);
+    Some(vec![TextEdit::new(Range::new(Position::new(0, 0), end), formatted)])
+}
+
+/// Slint's formatting rules are not local to a range - the correct indentation of a line depends
+/// on the nesting depth of everything above it - so a range-format request just formats the
+/// whole document and lets the client apply the overlapping part of the edit.
+pub fn format_range(
+    document_cache: &mut DocumentCache,
+    text_document: TextDocumentIdentifier,
+    _range: Range,
+) -> Option<Vec<TextEdit>> {
+    format_document(document_cache, text_document)
+}
+
+fn has_syntax_error(node: &SyntaxNode) -> bool {
+    node.kind() == SyntaxKind::Error || node.children().any(|child| has_syntax_error(&child))
+}
+
+fn format_node(node: &SyntaxNode) -> String {
+    let mut out = String::new();
+    let mut depth = 0u32;
+    for element in node.descendants_with_tokens() {
+        let token = match element.as_token() {
+            Some(token) => token,
+            None => continue,
+        };
+        match token.kind() {
+            SyntaxKind::LBrace => {
+                trim_trailing_spaces(&mut out);
+                if !out.is_empty() && !out.ends_with('\n') {
+                    out.push(' ');
+                }
+                out.push('{');
+                depth += 1;
+            }
+            SyntaxKind::RBrace => {
+                depth = depth.saturating_sub(1);
+                trim_trailing_whitespace_and_blank_lines(&mut out);
+                out.push('\n');
+                push_indent(&mut out, depth);
+                out.push('}');
+            }
+            SyntaxKind::Whitespace => {
+                let newlines = token.text().matches('\n').count();
+                if newlines == 0 {
+                    if !out.ends_with(' ') && !out.ends_with('\n') {
+                        out.push(' ');
+                    }
+                } else {
+                    // Collapse any run of blank lines down to at most one.
+                    trim_trailing_whitespace_and_blank_lines(&mut out);
+                    out.push('\n');
+                    if newlines > 1 {
+                        out.push('\n');
+                    }
+                    push_indent(&mut out, depth);
+                }
+            }
+            SyntaxKind::Colon => {
+                trim_trailing_spaces(&mut out);
+                out.push_str(": ");
+            }
+            SyntaxKind::ColonEqual => {
+                trim_trailing_spaces(&mut out);
+                if !out.is_empty() && !out.ends_with('\n') {
+                    out.push(' ');
+                }
+                out.push_str(":= ");
+            }
+            _ => out.push_str(token.text()),
+        }
+    }
+    trim_trailing_whitespace_and_blank_lines(&mut out);
+    out.push('\n');
+    out
+}
+
+fn push_indent(out: &mut String, depth: u32) {
+    for _ in 0..depth {
+        out.push_str(INDENT);
+    }
+}
+
+fn trim_trailing_spaces(out: &mut String) {
+    while out.ends_with(' ') {
+        out.pop();
+    }
+}
+
+fn trim_trailing_whitespace_and_blank_lines(out: &mut String) {
+    while matches!(out.chars().last(), Some(' ') | Some('\n')) {
+        out.pop();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sixtyfps_compilerlib::diagnostics::BuildDiagnostics;
+
+    fn parse(source: &str) -> SyntaxNode {
+        sixtyfps_compilerlib::parser::parse(
+            source.to_string(),
+            None,
+            &mut BuildDiagnostics::default(),
+        )
+    }
+
+    #[test]
+    fn has_syntax_error_is_false_for_well_formed_source() {
+        let node = parse("Foo := Rectangle {\n  width: 10px;\n}\n");
+        assert!(!has_syntax_error(&node));
+    }
+
+    #[test]
+    fn has_syntax_error_is_true_for_a_malformed_binding() {
+        // A binding with no expression on the right of `:` leaves an `Error` node in the tree;
+        // formatting it risks mangling a file the user is still in the middle of typing, so
+        // `format_document` must not touch it.
+        let node = parse("Foo := Rectangle {\n  width: ;\n}\n");
+        assert!(has_syntax_error(&node));
+    }
+
+    #[test]
+    fn format_node_reindents_and_collapses_blank_lines() {
+        let node = parse("Foo := Rectangle {\n\n\n  width:10px;\n}\n");
+        assert_eq!(format_node(&node), "Foo := Rectangle {\n\n  width: 10px;\n}\n");
+    }
+
+    #[test]
+    fn push_indent_writes_two_spaces_per_depth() {
+        let mut out = String::new();
+        push_indent(&mut out, 3);
+        assert_eq!(out, "      ");
+    }
+
+    #[test]
+    fn trim_trailing_spaces_stops_at_a_newline() {
+        let mut out = "abc  \n  ".to_string();
+        trim_trailing_spaces(&mut out);
+        assert_eq!(out, "abc  \n");
+    }
+
+    #[test]
+    fn trim_trailing_whitespace_and_blank_lines_removes_both() {
+        let mut out = "abc\n\n  \n".to_string();
+        trim_trailing_whitespace_and_blank_lines(&mut out);
+        assert_eq!(out, "abc");
+    }
+}
@@ -0,0 +1,205 @@
+/* LICENSE BEGIN
+    This file is part of the SixtyFPS Project -- https://sixtyfps.io
+    Copyright (c) 2020 Olivier Goffart <olivier.goffart@sixtyfps.io>
+    Copyright (c) 2020 Simon Hausmann <simon.hausmann@sixtyfps.io>
+
+    SPDX-License-Identifier: GPL-3.0-only
+    This file is also available under commercial licensing terms.
+    Please contact info@sixtyfps.io for more information.
+LICENSE END */
+
+//! `textDocument/hover`: resolve the token under the cursor to a `langtype::Type` and render it
+//! as Markdown, reusing the same type lookup (`local_registry.lookup`) that
+//! `show_preview_command` already uses to detect `Window` components.
+
+use crate::DocumentCache;
+use lsp_types::{Hover, HoverContents, MarkupContent, MarkupKind};
+use sixtyfps_compilerlib::langtype::Type;
+use sixtyfps_compilerlib::parser::{
+    identifier_text, syntax_nodes, SyntaxKind, SyntaxNode, SyntaxToken,
+};
+
+pub fn get_tooltip(document_cache: &mut DocumentCache, token: SyntaxToken) -> Option<Hover> {
+    let doc = document_cache.documents.get_document(token.source_file.path())?;
+    let text = token.text().to_string();
+    let node = token.parent();
+
+    // The name of an element instantiation, e.g. `Window` in `Window { ... }`.
+    if let Some(qualified_name) = syntax_nodes::QualifiedName::new(node.clone()) {
+        if let Some(element) = syntax_nodes::Element::new(qualified_name.parent()?) {
+            let ty = doc.local_registry.lookup(&text);
+            if !matches!(ty, Type::Invalid) {
+                let declaring_component = enclosing_component_name(&element.node);
+                return Some(type_hover(&text, &ty, declaring_component.as_deref()));
+            }
+        }
+
+        // A property access inside an expression or binding, e.g. `width` in `root.width: 10px;`
+        // - the qualified name's first segment names an element (`root`, `self`, `parent`, or,
+        // as a best-effort fallback, anything else) and its last segment is the accessed
+        // property, resolved through that element's base type the same way
+        // `component_is_window` resolves a base type's own name.
+        else if let [element_ref, property_name] =
+            identifier_segments(&qualified_name.node).as_slice()
+        {
+            if property_name == &text {
+                if let Some(element) = resolve_element_reference(&node, element_ref) {
+                    if let Some(base_type_name) = element_base_type_name(&element) {
+                        let base_type = doc.local_registry.lookup(&base_type_name);
+                        if let Some(prop_ty) = lookup_property(&base_type, &text) {
+                            let mut value =
+                                format!("```slint\nproperty <{}> {}\n```", prop_ty, text);
+                            value.push_str(&format!("\n\nDeclared in `{}`.", base_type_name));
+                            return Some(markdown_hover(value));
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    // The name in a declaration is an `Identifier` token inside a `DeclaredIdentifier` node.
+    let declaration =
+        if node.kind() == SyntaxKind::DeclaredIdentifier { node.parent() } else { None };
+
+    // A property's own declaration, e.g. the `x` in `property <length> x;`.
+    if let Some(prop) = declaration.clone().and_then(syntax_nodes::PropertyDeclaration::new) {
+        let name = identifier_text(&prop.DeclaredIdentifier())?;
+        if name == text {
+            let ty = prop.Type().map(|n| n.text().to_string()).unwrap_or_default();
+            return Some(markdown_hover(format!("```slint\nproperty <{}> {}\n```", ty, name)));
+        }
+    }
+
+    // A callback declaration, e.g. the `clicked` in `callback clicked();`.
+    if let Some(callback) = declaration.and_then(syntax_nodes::CallbackDeclaration::new) {
+        let name = identifier_text(&callback.DeclaredIdentifier())?;
+        if name == text {
+            let signature = callback_signature_text(&callback.node, &name);
+            let label =
+                if signature.is_empty() { name.clone() } else { format!("{}{}", name, signature) };
+            return Some(markdown_hover(format!("```slint\ncallback {}\n```", label)));
+        }
+    }
+
+    None
+}
+
+/// Collects the `Identifier` tokens of a `QualifiedName`, e.g. `["root", "width"]` for
+/// `root.width`.
+fn identifier_segments(node: &SyntaxNode) -> Vec<String> {
+    node.children_with_tokens()
+        .filter_map(|c| c.into_token())
+        .filter(|t| t.kind() == SyntaxKind::Identifier)
+        .map(|t| t.text().to_string())
+        .collect()
+}
+
+/// Resolves `root`/`self`/`parent` (or, as a best-effort fallback, any other identifier - ids
+/// declared via `foo := Element { ... }` aren't tracked here) to the element syntax node they
+/// refer to from `context`, by walking up its enclosing `Element` ancestors (the `SubElement`
+/// wrapping each nested `Element` is skipped, so the second ancestor is the parent element).
+fn resolve_element_reference(context: &SyntaxNode, name: &str) -> Option<SyntaxNode> {
+    let enclosing_elements: Vec<SyntaxNode> =
+        std::iter::successors(Some(context.clone()), |n| n.parent())
+            .filter(|n| n.kind() == SyntaxKind::Element)
+            .collect();
+    match name {
+        "parent" => enclosing_elements.get(1).cloned(),
+        "root" => enclosing_elements.last().cloned(),
+        _ => enclosing_elements.first().cloned(),
+    }
+}
+
+/// The base type name of an `Element`/`SubElement` syntax node, e.g. `Rectangle` in
+/// `Rectangle { ... }`.
+fn element_base_type_name(element: &SyntaxNode) -> Option<String> {
+    element.child_node(SyntaxKind::QualifiedName).and_then(|n| identifier_text(&n))
+}
+
+/// Walks `ty`'s inheritance chain - the component's own declared properties first, then each
+/// base type's in turn - to find `name`, mirroring the `Type::Component` unwrapping loop
+/// `component_is_window` already uses to walk to a base type.
+fn lookup_property(ty: &Type, name: &str) -> Option<Type> {
+    let mut ty = ty.clone();
+    loop {
+        match ty {
+            Type::Component(ref c) => {
+                let element = c.root_element.borrow();
+                if let Some(decl) = element.property_declarations.get(name) {
+                    return Some(decl.property_type.clone());
+                }
+                ty = element.base_type.clone();
+            }
+            Type::Builtin(ref b) => return b.properties.get(name).cloned(),
+            _ => return None,
+        }
+    }
+}
+
+/// Walks up from `node` to find the component it's declared in, e.g. to name "declared in
+/// `MyApp`" for an element instantiated inside `component MyApp { ... }`.
+fn enclosing_component_name(node: &SyntaxNode) -> Option<String> {
+    let mut current = node.parent();
+    while let Some(n) = current {
+        if let Some(component) = syntax_nodes::Component::new(n.clone()) {
+            return identifier_text(&component.DeclaredIdentifier());
+        }
+        current = n.parent();
+    }
+    None
+}
+
+/// Renders a callback declaration's parameter/return types, e.g. `(int, string) -> bool`, by
+/// replaying the source text after its name up to the trailing `;` - the parser doesn't expose
+/// the argument/return types as separate named children.
+fn callback_signature_text(node: &SyntaxNode, name: &str) -> String {
+    let full = node.text().to_string();
+    let after_name = full.find(name).map(|i| &full[i + name.len()..]).unwrap_or("");
+    after_name.trim().trim_end_matches(';').trim().to_string()
+}
+
+fn type_hover(name: &str, ty: &Type, declaring_component: Option<&str>) -> Hover {
+    let mut value = format!("```slint\n{}\n```", describe_type(name, ty));
+    if let Some(component) = declaring_component {
+        value.push_str(&format!("\n\nDeclared in `{}`.", component));
+    }
+    if let Some(doc) = builtin_description(ty) {
+        value.push_str(&format!("\n\n{}", doc));
+    }
+    markdown_hover(value)
+}
+
+fn describe_type(name: &str, ty: &Type) -> String {
+    match ty {
+        Type::Component(_) => format!("component {}", name),
+        Type::Builtin(b) => format!("component {} // builtin", b.name),
+        other => format!("{}: {}", name, other),
+    }
+}
+
+/// A one-line description for the handful of builtin elements users hover over most often.
+fn builtin_description(ty: &Type) -> Option<&'static str> {
+    let name = match ty {
+        Type::Builtin(b) => b.name.as_str(),
+        Type::Component(c) => match c.root_element.borrow().base_type {
+            Type::Builtin(ref b) => b.name.as_str(),
+            _ => return None,
+        },
+        _ => return None,
+    };
+    Some(match name {
+        "Window" => "The root element of a component shown in its own window.",
+        "Rectangle" => "A basic rectangle, optionally with rounded corners and a border.",
+        "Text" => "Renders a run of text.",
+        "Image" => "Renders a bitmap or vector image.",
+        _ => return None,
+    })
+}
+
+fn markdown_hover(value: String) -> Hover {
+    Hover {
+        contents: HoverContents::Markup(MarkupContent { kind: MarkupKind::Markdown, value }),
+        range: None,
+    }
+}
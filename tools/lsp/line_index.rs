@@ -0,0 +1,236 @@
+/* LICENSE BEGIN
+    This file is part of the SixtyFPS Project -- https://sixtyfps.io
+    Copyright (c) 2020 Olivier Goffart <olivier.goffart@sixtyfps.io>
+    Copyright (c) 2020 Simon Hausmann <simon.hausmann@sixtyfps.io>
+
+    SPDX-License-Identifier: GPL-3.0-only
+    This file is also available under commercial licensing terms.
+    Please contact info@sixtyfps.io for more information.
+LICENSE END */
+
+//! Translation between byte offsets (what the compiler and rowan syntax tree use) and LSP
+//! `Position`s, whose `character` field counts UTF-16 code units rather than bytes.
+
+use lsp_types::{Position, Range};
+use std::collections::HashMap;
+
+/// Maps between byte offsets and UTF-16 LSP positions for a single document.
+///
+/// For lines made up entirely of ASCII, `character == byte offset` within the line, so most
+/// lines need no extra bookkeeping. Lines containing multi-byte characters get a sorted list of
+/// `(utf16_column, byte_offset)` breakpoints, one per non-ASCII character, which `position_to_offset` and
+/// `offset_to_position` binary-search. A line's trailing `\r` (if any, for CRLF documents) is
+/// just another ASCII byte as far as this index is concerned.
+#[derive(Clone, Debug, Default)]
+pub struct LineIndex {
+    /// Byte offset of the start of each line, including line 0.
+    line_starts: Vec<u32>,
+    utf16_lines: HashMap<u32, Vec<(u32, u32)>>,
+}
+
+impl LineIndex {
+    pub fn new(text: &str) -> Self {
+        let mut index = Self { line_starts: vec![0], utf16_lines: HashMap::new() };
+        index.rebuild_from(text, 0, 0);
+        index
+    }
+
+    /// Translate an LSP (UTF-16) position into a byte offset into the document.
+    pub fn position_to_offset(&self, pos: Position) -> Option<u32> {
+        let line_start = *self.line_starts.get(pos.line as usize)?;
+        let character = pos.character;
+        Some(
+            line_start
+                + match self.utf16_lines.get(&pos.line) {
+                    None => character,
+                    Some(breakpoints) => {
+                        match breakpoints.binary_search_by_key(&character, |&(col, _)| col) {
+                            Ok(idx) => breakpoints[idx].1,
+                            Err(0) => character,
+                            Err(idx) => {
+                                let (prev_col, prev_byte) = breakpoints[idx - 1];
+                                prev_byte + (character - prev_col)
+                            }
+                        }
+                    }
+                },
+        )
+    }
+
+    /// Translate a byte offset into the document back into an LSP (UTF-16) position.
+    pub fn offset_to_position(&self, offset: u32) -> Position {
+        let line = match self.line_starts.binary_search(&offset) {
+            Ok(line) => line,
+            Err(next_line) => next_line.saturating_sub(1),
+        } as u32;
+        let byte_col = offset - self.line_starts[line as usize];
+        let character = match self.utf16_lines.get(&line) {
+            None => byte_col,
+            Some(breakpoints) => match breakpoints.binary_search_by_key(&byte_col, |&(_, b)| b) {
+                Ok(idx) => breakpoints[idx].0,
+                Err(0) => byte_col,
+                Err(idx) => {
+                    let (prev_col, prev_byte) = breakpoints[idx - 1];
+                    prev_col + (byte_col - prev_byte)
+                }
+            },
+        };
+        Position::new(line, character)
+    }
+
+    /// Apply an incremental `DidChangeTextDocument` edit: replace the text covered by `range`
+    /// (translated through the index *before* the edit) with `new_text` in `buffer`, then
+    /// recompute only the line entries from `range.start.line` onward - lines before the edit
+    /// keep their offsets unchanged.
+    pub fn splice(&mut self, buffer: &mut String, range: Range, new_text: &str) {
+        let first_line = range.start.line;
+        let first_offset = match self.line_starts.get(first_line as usize) {
+            Some(&offset) => offset as usize,
+            None => {
+                // `range` refers to a line we don't have - a stale range from a desynced client,
+                // or a `DidChange` for a document that was never `DidOpen`'d (so `buffer`/`self`
+                // are still empty). There's nothing sensible to splice into, so resync by treating
+                // `new_text` as the document's full contents rather than indexing past the end of
+                // `line_starts`, which would panic the caller's thread.
+                *buffer = new_text.to_owned();
+                *self = Self::new(buffer);
+                return;
+            }
+        };
+        let start = self.position_to_offset(range.start).unwrap_or(0) as usize;
+        let end = self.position_to_offset(range.end).unwrap_or(buffer.len() as u32) as usize;
+        buffer.replace_range(start..end, new_text);
+        self.rebuild_from(buffer, first_line, first_offset);
+    }
+
+    /// Recompute line starts and UTF-16 breakpoints from `first_offset` (the byte offset at
+    /// which `first_line` starts) to the end of `text`. Entries before `first_line` are kept.
+    fn rebuild_from(&mut self, text: &str, first_line: u32, first_offset: usize) {
+        self.line_starts.truncate(first_line as usize + 1);
+        self.utf16_lines.retain(|line, _| *line < first_line);
+
+        let mut offset = first_offset as u32;
+        let mut line = first_line;
+        // `split` (rather than `split_terminator`) deliberately keeps a trailing empty entry
+        // when `text` ends in a newline, so an empty final line still gets a `line_starts` slot.
+        let mut lines = text[first_offset..].split('\n').peekable();
+        while let Some(content) = lines.next() {
+            let mut utf16_col = 0u32;
+            let mut byte_off = 0u32;
+            let mut breakpoints = Vec::new();
+            for ch in content.chars() {
+                utf16_col += ch.len_utf16() as u32;
+                byte_off += ch.len_utf8() as u32;
+                // Breakpoints record the position *after* the character they belong to, so that
+                // everything strictly between two breakpoints (or before the first/after the
+                // last) is a run of ASCII where the byte/UTF-16 delta really is 1:1. Recording the
+                // pre-character position instead would make that assumption false for the
+                // character the breakpoint is named after, landing lookups mid-character.
+                if !ch.is_ascii() {
+                    breakpoints.push((utf16_col, byte_off));
+                }
+            }
+            if !breakpoints.is_empty() {
+                self.utf16_lines.insert(line, breakpoints);
+            }
+            offset += content.len() as u32;
+            if lines.peek().is_some() {
+                offset += 1; // the '\n' separator itself
+                line += 1;
+                self.line_starts.push(offset);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ascii_round_trips_1_to_1() {
+        let index = LineIndex::new("abc\ndef");
+        assert_eq!(index.position_to_offset(Position::new(0, 2)), Some(2));
+        assert_eq!(index.position_to_offset(Position::new(1, 1)), Some(5));
+        assert_eq!(index.offset_to_position(5), Position::new(1, 1));
+    }
+
+    #[test]
+    fn multi_byte_line_round_trips_on_and_around_every_char() {
+        // a=1B/1u16 ©=2B/1u16 b=1B/1u16 €=3B/1u16 c=1B/1u16
+        let index = LineIndex::new("a©b€c");
+        let cases = [
+            (0u32, 0u32), // before 'a'
+            (1, 1),       // before '©', after 'a'
+            (2, 3),       // before 'b', after '©'
+            (3, 4),       // before '€', after 'b'
+            (4, 7),       // before 'c', after '€'
+            (5, 8),       // end of line
+        ];
+        for (col, byte) in cases {
+            assert_eq!(
+                index.position_to_offset(Position::new(0, col)),
+                Some(byte),
+                "position_to_offset({})",
+                col
+            );
+            assert_eq!(
+                index.offset_to_position(byte),
+                Position::new(0, col),
+                "offset_to_position({})",
+                byte
+            );
+        }
+    }
+
+    #[test]
+    fn crlf_treats_carriage_return_as_an_ordinary_ascii_byte() {
+        let index = LineIndex::new("a©\r\nb");
+        // '\r' sits between '©' (ending at byte 3 / col 2) and the line's end.
+        assert_eq!(index.position_to_offset(Position::new(0, 3)), Some(4));
+        assert_eq!(index.position_to_offset(Position::new(1, 0)), Some(5));
+    }
+
+    #[test]
+    fn trailing_newline_gives_an_empty_final_line() {
+        let index = LineIndex::new("abc\n");
+        assert_eq!(index.position_to_offset(Position::new(1, 0)), Some(4));
+        assert_eq!(index.offset_to_position(4), Position::new(1, 0));
+    }
+
+    #[test]
+    fn splice_on_a_multi_byte_line_does_not_panic_and_reindexes_correctly() {
+        let mut buffer = "a©b€c\nsecond".to_string();
+        let mut index = LineIndex::new(&buffer);
+        // Replace "b" (col 2..3, byte 3..4) with "XY".
+        let range = Range::new(Position::new(0, 2), Position::new(0, 3));
+        index.splice(&mut buffer, range, "XY");
+        assert_eq!(buffer, "a©XY€c\nsecond");
+        // The edit made the first line one UTF-16 column and one byte longer; re-deriving the
+        // position of '€' (now at col 4, byte 5) must not panic and must land on its start.
+        assert_eq!(index.position_to_offset(Position::new(0, 4)), Some(5));
+        assert_eq!(index.position_to_offset(Position::new(1, 3)), buffer.find("ond").map(|o| o as u32));
+    }
+
+    #[test]
+    fn splice_spanning_multiple_lines_reindexes_from_the_start_line() {
+        let mut buffer = "one\ntwo\nthree".to_string();
+        let mut index = LineIndex::new(&buffer);
+        let range = Range::new(Position::new(0, 1), Position::new(1, 1));
+        index.splice(&mut buffer, range, "XX");
+        assert_eq!(buffer, "oXXwo\nthree");
+        assert_eq!(index.position_to_offset(Position::new(1, 0)), Some(6));
+    }
+
+    #[test]
+    fn splice_with_a_line_past_the_end_resyncs_instead_of_panicking() {
+        // A never-`DidOpen`'d document's index is empty; `position_to_offset` copes via `.get()`,
+        // and `splice` must too instead of indexing `line_starts` unchecked.
+        let mut buffer = String::new();
+        let mut index = LineIndex::new(&buffer);
+        let range = Range::new(Position::new(3, 0), Position::new(5, 2));
+        index.splice(&mut buffer, range, "whole new document");
+        assert_eq!(buffer, "whole new document");
+        assert_eq!(index.position_to_offset(Position::new(0, 6)), Some(6));
+    }
+}
@@ -8,23 +8,39 @@
     Please contact info@sixtyfps.io for more information.
 LICENSE END */
 
+mod code_lens;
 mod completion;
+mod concurrency;
+mod formatting;
 mod goto;
+mod hover;
+mod line_index;
 mod lsp_ext;
+#[cfg(not(target_arch = "wasm32"))]
 mod preview;
+#[cfg(target_arch = "wasm32")]
+mod server;
+mod symbols;
 mod util;
+#[cfg(target_arch = "wasm32")]
+mod wasm;
 
 use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
 
-use lsp_server::{Connection, Message, Request, RequestId, Response};
+use concurrency::RequestTracker;
+use line_index::LineIndex;
+use lsp_server::{Connection, ErrorCode, Message, Request, RequestId, Response};
 use lsp_types::notification::{DidChangeTextDocument, DidOpenTextDocument, Notification};
-use lsp_types::request::{CodeActionRequest, ExecuteCommand, GotoDefinition};
-use lsp_types::request::{Completion, HoverRequest};
+use lsp_types::request::{CodeActionRequest, CodeLensRequest, ExecuteCommand, GotoDefinition};
+use lsp_types::request::{Completion, DocumentSymbolRequest, HoverRequest, WorkspaceSymbol};
+use lsp_types::request::{Formatting, RangeFormatting};
 use lsp_types::{
-    CodeActionOrCommand, CodeActionProviderCapability, Command, CompletionOptions,
-    DidChangeTextDocumentParams, DidOpenTextDocumentParams, ExecuteCommandOptions, Hover,
-    HoverProviderCapability, InitializeParams, OneOf, Position, PublishDiagnosticsParams, Range,
-    ServerCapabilities, TextDocumentSyncCapability, Url, WorkDoneProgressOptions,
+    CodeActionOrCommand, CodeActionProviderCapability, CodeLensOptions, Command,
+    CompletionOptions, DidChangeTextDocumentParams, DidOpenTextDocumentParams,
+    ExecuteCommandOptions, HoverProviderCapability, InitializeParams, OneOf, Position,
+    PublishDiagnosticsParams, Range, ServerCapabilities, TextDocumentContentChangeEvent,
+    TextDocumentSyncCapability, Url, WorkDoneProgressOptions,
 };
 use sixtyfps_compilerlib::diagnostics::BuildDiagnostics;
 use sixtyfps_compilerlib::langtype::Type;
@@ -35,33 +51,31 @@ use sixtyfps_compilerlib::CompilerConfiguration;
 
 type Error = Box<dyn std::error::Error>;
 
-const SHOW_PREVIEW_COMMAND: &str = "showPreview";
+pub(crate) const SHOW_PREVIEW_COMMAND: &str = "showPreview";
 
+/// `Clone` because cloning a `TypeLoader`'s cached documents is cheap - they're reference-counted
+/// rowan syntax trees and `langtype::Type`s, same as every other place in this crate that clones a
+/// `SyntaxNode`. Note that those `Rc`s also mean `DocumentCache` is not `Send`: every request,
+/// read-only or not, is computed synchronously on the main message loop thread - see
+/// [`run_read_only`].
+#[derive(Clone)]
 pub struct DocumentCache<'a> {
-    documents: TypeLoader<'a>,
-    newline_offsets: HashMap<Url, Vec<u32>>,
+    pub(crate) documents: TypeLoader<'a>,
+    /// The last text sent to us for each open document, kept around so incremental
+    /// `DidChangeTextDocument` edits have something to splice into.
+    pub(crate) source_code: HashMap<Url, String>,
+    pub(crate) line_indices: HashMap<Url, LineIndex>,
 }
 
 impl<'a> DocumentCache<'a> {
     fn new(config: &'a CompilerConfiguration) -> Self {
         let documents =
             TypeLoader::new(TypeRegister::builtin(), config, &mut BuildDiagnostics::default());
-        Self { documents, newline_offsets: Default::default() }
-    }
-
-    fn newline_offsets_from_content(content: &str) -> Vec<u32> {
-        let mut ln_offs = 0;
-        content
-            .split('\n')
-            .map(|line| {
-                let r = ln_offs;
-                ln_offs += line.len() as u32 + 1;
-                r
-            })
-            .collect()
+        Self { documents, source_code: Default::default(), line_indices: Default::default() }
     }
 }
 
+#[cfg(not(target_arch = "wasm32"))]
 fn main() {
     std::thread::spawn(|| {
         match run_lsp_server() {
@@ -76,6 +90,13 @@ fn main() {
     preview::start_ui_event_loop();
 }
 
+/// The `wasm32` build is a `cdylib`-style library driven by [`wasm`] instead - there is no stdio
+/// to read a message loop from, and no native event loop to pump - but the `bin` target still
+/// needs a `main` to link.
+#[cfg(target_arch = "wasm32")]
+fn main() {}
+
+#[cfg(not(target_arch = "wasm32"))]
 fn run_lsp_server() -> Result<(), Error> {
     let (connection, io_threads) = Connection::stdio();
     let capabilities = ServerCapabilities {
@@ -90,9 +111,12 @@ fn run_lsp_server() -> Result<(), Error> {
         workspace_symbol_provider: Some(OneOf::Left(true)),
         definition_provider: Some(OneOf::Left(true)),
         text_document_sync: Some(TextDocumentSyncCapability::Kind(
-            lsp_types::TextDocumentSyncKind::Full,
+            lsp_types::TextDocumentSyncKind::Incremental,
         )),
         code_action_provider: Some(CodeActionProviderCapability::Simple(true)),
+        code_lens_provider: Some(CodeLensOptions { resolve_provider: Some(false) }),
+        document_formatting_provider: Some(OneOf::Left(true)),
+        document_range_formatting_provider: Some(OneOf::Left(true)),
         execute_command_provider: Some(ExecuteCommandOptions {
             commands: vec![SHOW_PREVIEW_COMMAND.into()],
             ..Default::default()
@@ -106,83 +130,129 @@ fn run_lsp_server() -> Result<(), Error> {
     Ok(())
 }
 
+#[cfg(not(target_arch = "wasm32"))]
 fn main_loop(connection: &Connection, params: serde_json::Value) -> Result<(), Error> {
-    let params: InitializeParams = serde_json::from_value(params).unwrap();
+    let params = Arc::new(serde_json::from_value::<InitializeParams>(params).unwrap());
     let mut compiler_config = sixtyfps_compilerlib::CompilerConfiguration::new(
         sixtyfps_compilerlib::generator::OutputFormat::Interpreter,
     );
     compiler_config.style = Some("ugly".into());
+    // The compiler configuration is immutable for the lifetime of the server, so leaking it
+    // turns it into a `'static` reference that `document_cache`, shared via `Arc` across the
+    // lifetime of the server, can hold onto without a lifetime parameter.
+    let compiler_config: &'static CompilerConfiguration = Box::leak(Box::new(compiler_config));
+
+    let document_cache = Arc::new(Mutex::new(DocumentCache::new(compiler_config)));
+    let requests = Arc::new(RequestTracker::default());
 
-    let mut document_cache = DocumentCache::new(&compiler_config);
     for msg in &connection.receiver {
         match msg {
             Message::Request(req) => {
                 if connection.handle_shutdown(&req)? {
                     return Ok(());
                 }
-                handle_request(connection, req, &params, &mut document_cache)?;
+                dispatch_request(connection, req, &params, &document_cache, &requests)?;
             }
             Message::Response(_resp) => {}
             Message::Notification(notifi) => {
-                handle_notification(connection, notifi, &mut document_cache)?
+                handle_notification(connection, notifi, &document_cache, &requests)?
             }
         }
     }
     Ok(())
 }
 
-fn handle_request(
+/// Dispatches a request. Read-only requests (completion, goto, hover, code actions, document and
+/// workspace symbols) go through [`run_read_only`], which registers them with `requests` - but
+/// the computation itself always runs synchronously, right here on the main loop thread, never on
+/// a separate worker: `DocumentCache` is built on `Rc`-based rowan trees and isn't `Send`, so
+/// there's no sound way to hand it (or a clone of it) to one. A `$/cancelRequest` or an edit is
+/// therefore only read after the response has been sent, and never turns it into
+/// `ContentModified`. `ExecuteCommand` has side effects (spawning the live preview) and is
+/// handled inline too.
+#[cfg(not(target_arch = "wasm32"))]
+fn dispatch_request(
     connection: &Connection,
     req: Request,
-    init_param: &InitializeParams,
-    document_cache: &mut DocumentCache,
+    init_param: &Arc<InitializeParams>,
+    document_cache: &Arc<Mutex<DocumentCache<'static>>>,
+    requests: &Arc<RequestTracker>,
 ) -> Result<(), Error> {
     let mut req = Some(req);
     if let Some((id, params)) = cast::<GotoDefinition>(&mut req) {
-        let result = token_descr(
-            document_cache,
-            params.text_document_position_params.text_document,
-            params.text_document_position_params.position,
-        )
-        .and_then(|token| goto::goto_definition(document_cache, token.0));
-        let resp = Response::new_ok(id, result);
-        connection.sender.send(Message::Response(resp))?;
+        let uri = params.text_document_position_params.text_document.uri.clone();
+        run_read_only(connection, id, uri, document_cache, requests, move |cache| {
+            token_descr(
+                cache,
+                params.text_document_position_params.text_document,
+                params.text_document_position_params.position,
+            )
+            .and_then(|token| goto::goto_definition(cache, token.0))
+        })?;
     } else if let Some((id, params)) = cast::<Completion>(&mut req) {
-        let result = token_descr(
-            document_cache,
-            params.text_document_position.text_document,
-            params.text_document_position.position,
-        )
-        .and_then(|token| {
-            completion::completion_at(
-                document_cache,
-                token.0,
-                token.1,
-                init_param.capabilities.text_document.as_ref().and_then(|t| t.completion.as_ref()),
+        let uri = params.text_document_position.text_document.uri.clone();
+        run_read_only(connection, id, uri, document_cache, requests, move |cache| {
+            token_descr(
+                cache,
+                params.text_document_position.text_document,
+                params.text_document_position.position,
             )
-        });
-        let resp = Response::new_ok(id, result);
-        connection.sender.send(Message::Response(resp))?;
-    } else if let Some((id, _params)) = cast::<HoverRequest>(&mut req) {
-        /*let result =
-            token_descr(document_cache, params.text_document_position_params).map(|x| Hover {
-                contents: lsp_types::HoverContents::Scalar(MarkedString::from_language_code(
-                    "text".into(),
-                    format!("{:?}", x.token),
-                )),
-                range: None,
-            });
-        let resp = Response::new_ok(id, result);
-        connection.sender.send(Message::Response(resp))?;*/
-        connection.sender.send(Message::Response(Response::new_ok(id, None::<Hover>)))?;
+            .and_then(|token| {
+                completion::completion_at(
+                    cache,
+                    token.0,
+                    token.1,
+                    init_param.capabilities.text_document.as_ref().and_then(|t| t.completion.as_ref()),
+                )
+            })
+        })?;
+    } else if let Some((id, params)) = cast::<HoverRequest>(&mut req) {
+        let uri = params.text_document_position_params.text_document.uri.clone();
+        run_read_only(connection, id, uri, document_cache, requests, move |cache| {
+            token_descr(
+                cache,
+                params.text_document_position_params.text_document,
+                params.text_document_position_params.position,
+            )
+            .and_then(|token| hover::get_tooltip(cache, token.0))
+        })?;
     } else if let Some((id, params)) = cast::<CodeActionRequest>(&mut req) {
-        let result = token_descr(document_cache, params.text_document, params.range.start)
-            .and_then(|token| get_code_actions(document_cache, token.0.parent()));
-        connection.sender.send(Message::Response(Response::new_ok(id, result)))?;
+        let uri = params.text_document.uri.clone();
+        run_read_only(connection, id, uri, document_cache, requests, move |cache| {
+            token_descr(cache, params.text_document, params.range.start)
+                .and_then(|token| get_code_actions(cache, token.0.parent()))
+        })?;
+    } else if let Some((id, params)) = cast::<DocumentSymbolRequest>(&mut req) {
+        let uri = params.text_document.uri.clone();
+        run_read_only(connection, id, uri, document_cache, requests, move |cache| {
+            symbols::document_symbols(cache, params.text_document)
+        })?;
+    } else if let Some((id, params)) = cast::<WorkspaceSymbol>(&mut req) {
+        // Not tied to a single document; a per-document edit never supersedes it.
+        let uri = Url::parse("urn:sixtyfps-lsp:workspace-symbol").unwrap();
+        run_read_only(connection, id, uri, document_cache, requests, move |cache| {
+            symbols::workspace_symbols(cache, &params)
+        })?;
+    } else if let Some((id, params)) = cast::<CodeLensRequest>(&mut req) {
+        let uri = params.text_document.uri.clone();
+        run_read_only(connection, id, uri, document_cache, requests, move |cache| {
+            code_lens::code_lens(cache, params.text_document)
+        })?;
+    } else if let Some((id, params)) = cast::<Formatting>(&mut req) {
+        let uri = params.text_document.uri.clone();
+        run_read_only(connection, id, uri, document_cache, requests, move |cache| {
+            formatting::format_document(cache, params.text_document)
+        })?;
+    } else if let Some((id, params)) = cast::<RangeFormatting>(&mut req) {
+        let uri = params.text_document.uri.clone();
+        run_read_only(connection, id, uri, document_cache, requests, move |cache| {
+            formatting::format_range(cache, params.text_document, params.range)
+        })?;
     } else if let Some((id, params)) = cast::<ExecuteCommand>(&mut req) {
+        let document_cache = document_cache.lock().unwrap();
         match params.command.as_str() {
             SHOW_PREVIEW_COMMAND => {
-                show_preview_command(&params.arguments, connection, document_cache)?
+                show_preview_command(&params.arguments, connection, &document_cache)?
             }
             _ => (),
         }
@@ -193,7 +263,44 @@ fn handle_request(
     Ok(())
 }
 
-fn cast<Kind: lsp_types::request::Request>(
+/// Registers `id` with `requests`, runs `compute` synchronously against the shared
+/// `document_cache` (holding its lock only for `compute`'s duration, same as every other
+/// request), and sends back the result - or `ContentModified` if `id`'s token is cancelled.
+/// There is no worker pool: `DocumentCache` is built on `Rc`-based rowan trees and isn't `Send`,
+/// so there's no sound way to hand it (or a clone of it) to a real OS thread. Every request -
+/// read-only or not - is computed right here, one at a time, on the main loop thread, so no
+/// other message is handled between registering `id` and completing it and the token is never
+/// found cancelled.
+#[cfg(not(target_arch = "wasm32"))]
+fn run_read_only<T: serde::Serialize>(
+    connection: &Connection,
+    id: RequestId,
+    uri: Url,
+    document_cache: &Arc<Mutex<DocumentCache<'static>>>,
+    requests: &Arc<RequestTracker>,
+    compute: impl FnOnce(&mut DocumentCache) -> T,
+) -> Result<(), Error> {
+    let token = requests.register(id.clone(), uri);
+    let response = if token.is_cancelled() {
+        Response::new_err(id.clone(), ErrorCode::ContentModified as i32, "content modified".into())
+    } else {
+        let result = compute(&mut document_cache.lock().unwrap());
+        if token.is_cancelled() {
+            Response::new_err(
+                id.clone(),
+                ErrorCode::ContentModified as i32,
+                "content modified".into(),
+            )
+        } else {
+            Response::new_ok(id.clone(), result)
+        }
+    };
+    requests.complete(&id);
+    connection.sender.send(Message::Response(response))?;
+    Ok(())
+}
+
+pub(crate) fn cast<Kind: lsp_types::request::Request>(
     req: &mut Option<Request>,
 ) -> Option<(RequestId, Kind::Params)> {
     match req.take().unwrap().extract::<Kind::Params>(Kind::METHOD) {
@@ -205,35 +312,62 @@ fn cast<Kind: lsp_types::request::Request>(
     }
 }
 
+/// `$/cancelRequest` is handled before `document_cache` is locked, ahead of the match below. It
+/// only flags the token of a request still registered with `requests`; since requests are
+/// computed one at a time on this thread, none is still registered by the time it is read.
+#[cfg(not(target_arch = "wasm32"))]
 fn handle_notification(
     connection: &Connection,
     req: lsp_server::Notification,
-    document_cache: &mut DocumentCache,
+    document_cache: &Arc<Mutex<DocumentCache<'static>>>,
+    requests: &Arc<RequestTracker>,
 ) -> Result<(), Error> {
+    if &*req.method == "$/cancelRequest" {
+        let params: lsp_types::CancelParams = serde_json::from_value(req.params)?;
+        let id = match params.id {
+            lsp_types::NumberOrString::Number(n) => RequestId::from(n),
+            lsp_types::NumberOrString::String(s) => RequestId::from(s),
+        };
+        requests.cancel(&id);
+        return Ok(());
+    }
+
+    let mut document_cache = document_cache.lock().unwrap();
     match &*req.method {
         DidOpenTextDocument::METHOD => {
             let params: DidOpenTextDocumentParams = serde_json::from_value(req.params)?;
+            requests.cancel_requests_for_document(&params.text_document.uri);
+            let line_index = LineIndex::new(&params.text_document.text);
             reload_document(
                 connection,
                 params.text_document.text,
+                line_index,
                 params.text_document.uri,
-                document_cache,
+                &mut document_cache,
             )?;
         }
         DidChangeTextDocument::METHOD => {
-            let mut params: DidChangeTextDocumentParams = serde_json::from_value(req.params)?;
-            reload_document(
-                connection,
-                params.content_changes.pop().unwrap().text,
-                params.text_document.uri,
-                document_cache,
-            )?;
+            let params: DidChangeTextDocumentParams = serde_json::from_value(req.params)?;
+            let uri = params.text_document.uri;
+            // This edit supersedes any read-only request still computing against the document's
+            // previous contents; no point letting it finish and send a stale response.
+            requests.cancel_requests_for_document(&uri);
+            let mut content = document_cache.source_code.get(&uri).cloned().unwrap_or_default();
+            let mut line_index = document_cache
+                .line_indices
+                .get(&uri)
+                .cloned()
+                .unwrap_or_else(|| LineIndex::new(&content));
+            for change in params.content_changes {
+                apply_content_change(&mut content, &mut line_index, change);
+            }
+            reload_document(connection, content, line_index, uri, &mut document_cache)?;
         }
         "sixtyfps/showPreview" => {
             show_preview_command(
                 req.params.as_array().map_or(&[], |x| x.as_slice()),
                 connection,
-                document_cache,
+                &document_cache,
             )?;
         }
         _ => (),
@@ -241,6 +375,7 @@ fn handle_notification(
     Ok(())
 }
 
+#[cfg(not(target_arch = "wasm32"))]
 fn show_preview_command(
     params: &[serde_json::Value],
     connection: &Connection,
@@ -256,13 +391,7 @@ fn show_preview_command(
     let component = params.get(1).and_then(|v| v.as_str()).map(|v| v.to_string());
     let is_window = component
         .as_ref()
-        .and_then(|c| {
-            let mut ty = document_cache.documents.get_document(&path)?.local_registry.lookup(&c);
-            while let Type::Component(c) = ty {
-                ty = c.root_element.borrow().base_type.clone();
-            }
-            Some(matches!(ty, Type::Builtin(b) if b.name == "Window"))
-        })
+        .map(|c| component_is_window(document_cache, &path, c))
         .unwrap_or(false);
     preview::load_preview(
         connection.sender.clone(),
@@ -272,18 +401,79 @@ fn show_preview_command(
     Ok(())
 }
 
+/// Whether `component` (a top-level component name in the document at `path`) resolves,
+/// transitively through its base types, to the builtin `Window` element. Shared between
+/// `show_preview_command` (to decide whether the preview window should itself be used as the
+/// top-level window) and the "Set as live preview" code lens.
+pub(crate) fn component_is_window(
+    document_cache: &DocumentCache,
+    path: &std::path::Path,
+    component: &str,
+) -> bool {
+    (|| {
+        let mut ty = document_cache.documents.get_document(path)?.local_registry.lookup(component);
+        while let Type::Component(c) = ty {
+            ty = c.root_element.borrow().base_type.clone();
+        }
+        Some(matches!(ty, Type::Builtin(b) if b.name == "Window"))
+    })()
+    .unwrap_or(false)
+}
+
+/// Apply one `content_changes` entry to `content`/`line_index` in place. A change without a
+/// `range` is a full-document replacement (sent by some clients even under incremental sync);
+/// otherwise the edit is spliced into the existing buffer and only the affected line entries of
+/// `line_index` are rebuilt.
+pub(crate) fn apply_content_change(
+    content: &mut String,
+    line_index: &mut LineIndex,
+    change: TextDocumentContentChangeEvent,
+) {
+    match change.range {
+        Some(range) => line_index.splice(content, range, &change.text),
+        None => {
+            *content = change.text;
+            *line_index = LineIndex::new(content);
+        }
+    }
+}
+
+/// Native wrapper around [`reload_document_core`]: also feeds the new contents to the native
+/// preview window, then forwards the diagnostics it computed to `connection`.
+#[cfg(not(target_arch = "wasm32"))]
 fn reload_document(
     connection: &Connection,
     content: String,
+    line_index: LineIndex,
     uri: lsp_types::Url,
     document_cache: &mut DocumentCache,
 ) -> Result<(), Error> {
-    let newline_offsets = DocumentCache::newline_offsets_from_content(&content);
-    document_cache.newline_offsets.insert(uri.clone(), newline_offsets);
-
     let path = uri.to_file_path().unwrap();
     let path_canon = path.canonicalize().unwrap_or_else(|_| path.to_owned());
     preview::set_contents(&path_canon, content.clone());
+    for message in reload_document_core(document_cache, content, line_index, uri)? {
+        connection.sender.send(message)?;
+    }
+    Ok(())
+}
+
+/// Recompiles `content` for `uri` and returns the `textDocument/publishDiagnostics` notifications
+/// to send (empty diagnostics for a file clear any it previously reported). Transport-agnostic: it
+/// only touches `document_cache` and has no knowledge of `Connection` or the native preview
+/// window, which is what lets [`crate::server::Server`] call it directly for the `wasm32` build;
+/// [`reload_document`] wraps it for the native stdio server, which also needs to push the new
+/// contents to the native preview.
+pub(crate) fn reload_document_core(
+    document_cache: &mut DocumentCache,
+    content: String,
+    line_index: LineIndex,
+    uri: lsp_types::Url,
+) -> Result<Vec<Message>, Error> {
+    document_cache.line_indices.insert(uri.clone(), line_index);
+    document_cache.source_code.insert(uri.clone(), content.clone());
+
+    let path = uri.to_file_path().unwrap();
+    let path_canon = path.canonicalize().unwrap_or_else(|_| path.to_owned());
     let mut diag = BuildDiagnostics::default();
     spin_on::spin_on(document_cache.documents.load_file(&path_canon, &path, content, &mut diag));
 
@@ -301,17 +491,19 @@ fn reload_document(
             continue;
         }
         let uri = Url::from_file_path(d.source_file().unwrap()).unwrap();
-        lsp_diags.entry(uri).or_default().push(to_lsp_diag(&d));
+        let line_index = document_cache.line_indices.get(&uri);
+        lsp_diags.entry(uri).or_default().push(to_lsp_diag(line_index, &d));
     }
 
-    for (uri, diagnostics) in lsp_diags {
-        connection.sender.send(Message::Notification(lsp_server::Notification::new(
-            "textDocument/publishDiagnostics".into(),
-            PublishDiagnosticsParams { uri, diagnostics, version: None },
-        )))?;
-    }
-
-    Ok(())
+    Ok(lsp_diags
+        .into_iter()
+        .map(|(uri, diagnostics)| {
+            Message::Notification(lsp_server::Notification::new(
+                "textDocument/publishDiagnostics".into(),
+                PublishDiagnosticsParams { uri, diagnostics, version: None },
+            ))
+        })
+        .collect())
 }
 
 fn to_lsp_diag_level(
@@ -323,9 +515,12 @@ fn to_lsp_diag_level(
     }
 }
 
-fn to_lsp_diag(d: &sixtyfps_compilerlib::diagnostics::Diagnostic) -> lsp_types::Diagnostic {
+fn to_lsp_diag(
+    line_index: Option<&LineIndex>,
+    d: &sixtyfps_compilerlib::diagnostics::Diagnostic,
+) -> lsp_types::Diagnostic {
     lsp_types::Diagnostic::new(
-        to_range(d.line_column()),
+        to_range(line_index, d.line_column()),
         Some(to_lsp_diag_level(d.level())),
         None,
         None,
@@ -335,19 +530,26 @@ fn to_lsp_diag(d: &sixtyfps_compilerlib::diagnostics::Diagnostic) -> lsp_types::
     )
 }
 
-fn to_range(span: (usize, usize)) -> Range {
-    let pos = Position::new((span.0 as u32).saturating_sub(1), (span.1 as u32).saturating_sub(1));
-    Range::new(pos, pos)
+/// Diagnostics only carry a start position, so the best we can do without a token to anchor on
+/// is report a minimal but non-empty range - that way clients that underline or fade out the
+/// affected range (rather than just showing a squiggle at a point) still highlight something.
+/// For a file with no line index (one that was never opened), the range stays zero-width.
+fn to_range(line_index: Option<&LineIndex>, span: (usize, usize)) -> Range {
+    let start = Position::new((span.0 as u32).saturating_sub(1), (span.1 as u32).saturating_sub(1));
+    let end = line_index
+        .and_then(|index| Some((index, index.position_to_offset(start)?)))
+        .map(|(index, offset)| index.offset_to_position(offset + 1))
+        .unwrap_or(start);
+    Range::new(start, end)
 }
 
 /// return the token, and the offset within the file
-fn token_descr(
+pub(crate) fn token_descr(
     document_cache: &mut DocumentCache,
     text_document: lsp_types::TextDocumentIdentifier,
     pos: Position,
 ) -> Option<(SyntaxToken, u32)> {
-    let o = document_cache.newline_offsets.get(&text_document.uri)?.get(pos.line as usize)?
-        + pos.character as u32;
+    let o = document_cache.line_indices.get(&text_document.uri)?.position_to_offset(pos)?;
 
     let doc = document_cache.documents.get_document(&text_document.uri.to_file_path().ok()?)?;
     let node = doc.node.as_ref()?;
@@ -376,7 +578,7 @@ fn token_descr(
     Some((SyntaxToken { token, source_file: node.source_file.clone() }, o))
 }
 
-fn get_code_actions(
+pub(crate) fn get_code_actions(
     _document_cache: &mut DocumentCache,
     node: SyntaxNode,
 ) -> Option<Vec<CodeActionOrCommand>> {
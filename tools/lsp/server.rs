@@ -0,0 +1,185 @@
+/* LICENSE BEGIN
+    This file is part of the SixtyFPS Project -- https://sixtyfps.io
+    Copyright (c) 2020 Olivier Goffart <olivier.goffart@sixtyfps.io>
+    Copyright (c) 2020 Simon Hausmann <simon.hausmann@sixtyfps.io>
+
+    SPDX-License-Identifier: GPL-3.0-only
+    This file is also available under commercial licensing terms.
+    Please contact info@sixtyfps.io for more information.
+LICENSE END */
+
+//! A transport-agnostic core for the `wasm32` in-browser adapter (see [`crate::wasm`]); this
+//! module is only compiled for `wasm32`. Unlike `main_loop`/`dispatch_request`,
+//! [`Server::handle_message`] never touches `lsp_server::Connection` - it runs every request to
+//! completion on whatever thread calls it and hands back the messages to send in response. That's
+//! what lets it run inside a browser's single wasm thread; the native stdio binary doesn't go
+//! through this path.
+
+use crate::{
+    cast, component_is_window, get_code_actions, reload_document_core, token_descr, DocumentCache,
+    SHOW_PREVIEW_COMMAND,
+};
+use crate::line_index::LineIndex;
+use lsp_server::{Message, Notification as LspNotification, Request, Response};
+use lsp_types::notification::{DidChangeTextDocument, DidOpenTextDocument, Notification};
+use lsp_types::request::{
+    CodeActionRequest, CodeLensRequest, Completion, DocumentSymbolRequest, ExecuteCommand,
+    Formatting, GotoDefinition, HoverRequest, RangeFormatting, WorkspaceSymbol,
+};
+use lsp_types::{DidChangeTextDocumentParams, DidOpenTextDocumentParams, InitializeParams};
+use sixtyfps_compilerlib::CompilerConfiguration;
+use std::sync::Mutex;
+
+pub struct Server {
+    document_cache: Mutex<DocumentCache<'static>>,
+    init_param: InitializeParams,
+}
+
+impl Server {
+    pub fn new(init_param: InitializeParams, compiler_config: &'static CompilerConfiguration) -> Self {
+        Self { document_cache: Mutex::new(DocumentCache::new(compiler_config)), init_param }
+    }
+
+    /// Processes one `lsp_server::Message` and returns the messages to send back (zero or more -
+    /// a notification like `DidChange` can produce several `publishDiagnostics`, while most
+    /// requests produce exactly one `Response`).
+    pub fn handle_message(&self, msg: Message) -> Vec<Message> {
+        match msg {
+            Message::Request(req) => self.handle_request(req),
+            Message::Notification(note) => self.handle_notification(note),
+            Message::Response(_) => Vec::new(),
+        }
+    }
+
+    fn handle_request(&self, req: Request) -> Vec<Message> {
+        let mut cache = self.document_cache.lock().unwrap();
+        let mut req = Some(req);
+        if let Some((id, params)) = cast::<GotoDefinition>(&mut req) {
+            let result = token_descr(
+                &mut cache,
+                params.text_document_position_params.text_document,
+                params.text_document_position_params.position,
+            )
+            .and_then(|token| crate::goto::goto_definition(&mut cache, token.0));
+            return vec![Message::Response(Response::new_ok(id, result))];
+        }
+        if let Some((id, params)) = cast::<Completion>(&mut req) {
+            let trigger_chars = self
+                .init_param
+                .capabilities
+                .text_document
+                .as_ref()
+                .and_then(|t| t.completion.as_ref());
+            let result = token_descr(
+                &mut cache,
+                params.text_document_position.text_document,
+                params.text_document_position.position,
+            )
+            .and_then(|token| {
+                crate::completion::completion_at(&mut cache, token.0, token.1, trigger_chars)
+            });
+            return vec![Message::Response(Response::new_ok(id, result))];
+        }
+        if let Some((id, params)) = cast::<HoverRequest>(&mut req) {
+            let result = token_descr(
+                &mut cache,
+                params.text_document_position_params.text_document,
+                params.text_document_position_params.position,
+            )
+            .and_then(|token| crate::hover::get_tooltip(&mut cache, token.0));
+            return vec![Message::Response(Response::new_ok(id, result))];
+        }
+        if let Some((id, params)) = cast::<CodeActionRequest>(&mut req) {
+            let result = token_descr(&mut cache, params.text_document, params.range.start)
+                .and_then(|token| get_code_actions(&mut cache, token.0.parent()));
+            return vec![Message::Response(Response::new_ok(id, result))];
+        }
+        if let Some((id, params)) = cast::<DocumentSymbolRequest>(&mut req) {
+            let result = crate::symbols::document_symbols(&mut cache, params.text_document);
+            return vec![Message::Response(Response::new_ok(id, result))];
+        }
+        if let Some((id, params)) = cast::<WorkspaceSymbol>(&mut req) {
+            let result = crate::symbols::workspace_symbols(&mut cache, &params);
+            return vec![Message::Response(Response::new_ok(id, result))];
+        }
+        if let Some((id, params)) = cast::<CodeLensRequest>(&mut req) {
+            let result = crate::code_lens::code_lens(&mut cache, params.text_document);
+            return vec![Message::Response(Response::new_ok(id, result))];
+        }
+        if let Some((id, params)) = cast::<Formatting>(&mut req) {
+            let result = crate::formatting::format_document(&mut cache, params.text_document);
+            return vec![Message::Response(Response::new_ok(id, result))];
+        }
+        if let Some((id, params)) = cast::<RangeFormatting>(&mut req) {
+            let result =
+                crate::formatting::format_range(&mut cache, params.text_document, params.range);
+            return vec![Message::Response(Response::new_ok(id, result))];
+        }
+        if let Some((id, params)) = cast::<ExecuteCommand>(&mut req) {
+            let mut out = Vec::new();
+            if params.command == SHOW_PREVIEW_COMMAND {
+                out.extend(show_preview_event(&cache, &params.arguments));
+            }
+            out.push(Message::Response(Response::new_ok(id, None::<serde_json::Value>)));
+            return out;
+        }
+        Vec::new()
+    }
+
+    fn handle_notification(&self, note: LspNotification) -> Vec<Message> {
+        let mut cache = self.document_cache.lock().unwrap();
+        match &*note.method {
+            DidOpenTextDocument::METHOD => {
+                let params: DidOpenTextDocumentParams = match serde_json::from_value(note.params) {
+                    Ok(params) => params,
+                    Err(_) => return Vec::new(),
+                };
+                let line_index = LineIndex::new(&params.text_document.text);
+                reload_document_core(
+                    &mut cache,
+                    params.text_document.text,
+                    line_index,
+                    params.text_document.uri,
+                )
+                .unwrap_or_default()
+            }
+            DidChangeTextDocument::METHOD => {
+                let params: DidChangeTextDocumentParams = match serde_json::from_value(note.params)
+                {
+                    Ok(params) => params,
+                    Err(_) => return Vec::new(),
+                };
+                let uri = params.text_document.uri;
+                let mut content = cache.source_code.get(&uri).cloned().unwrap_or_default();
+                let mut line_index = cache
+                    .line_indices
+                    .get(&uri)
+                    .cloned()
+                    .unwrap_or_else(|| LineIndex::new(&content));
+                for change in params.content_changes {
+                    crate::apply_content_change(&mut content, &mut line_index, change);
+                }
+                reload_document_core(&mut cache, content, line_index, uri).unwrap_or_default()
+            }
+            _ => Vec::new(),
+        }
+    }
+}
+
+/// There is no native preview window to spawn in a browser; instead of calling into `preview`
+/// (which is cfg'd out on `wasm32` entirely), emit a notification the host page can render the
+/// component itself from, using the same `Window`-base-type detection `show_preview_command` uses
+/// natively.
+fn show_preview_event(document_cache: &DocumentCache, arguments: &[serde_json::Value]) -> Vec<Message> {
+    let path = match arguments.get(0).and_then(|v| v.as_str()) {
+        Some(s) => std::path::PathBuf::from(s),
+        None => return Vec::new(),
+    };
+    let component = arguments.get(1).and_then(|v| v.as_str()).map(|v| v.to_string());
+    let is_window =
+        component.as_ref().map(|c| component_is_window(document_cache, &path, c)).unwrap_or(false);
+    vec![Message::Notification(LspNotification::new(
+        "sixtyfps/previewRequested".into(),
+        serde_json::json!({ "path": path, "component": component, "isWindow": is_window }),
+    ))]
+}
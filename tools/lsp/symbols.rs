@@ -0,0 +1,191 @@
+/* LICENSE BEGIN
+    This file is part of the SixtyFPS Project -- https://sixtyfps.io
+    Copyright (c) 2020 Olivier Goffart <olivier.goffart@sixtyfps.io>
+    Copyright (c) 2020 Simon Hausmann <simon.hausmann@sixtyfps.io>
+
+    SPDX-License-Identifier: GPL-3.0-only
+    This file is also available under commercial licensing terms.
+    Please contact info@sixtyfps.io for more information.
+LICENSE END */
+
+//! `textDocument/documentSymbol` and `workspace/symbol` handlers.
+
+use crate::line_index::LineIndex;
+use crate::DocumentCache;
+use lsp_types::{
+    DocumentSymbol, DocumentSymbolResponse, Location, SymbolInformation, SymbolKind,
+    TextDocumentIdentifier, Url, WorkspaceSymbolParams, WorkspaceSymbolResponse,
+};
+use sixtyfps_compilerlib::parser::{identifier_text, syntax_nodes, SyntaxKind, SyntaxNode};
+
+pub fn document_symbols(
+    document_cache: &mut DocumentCache,
+    text_document: TextDocumentIdentifier,
+) -> Option<DocumentSymbolResponse> {
+    let uri = text_document.uri;
+    let line_index = document_cache.line_indices.get(&uri)?.clone();
+    let doc = document_cache.documents.get_document(&uri.to_file_path().ok()?)?;
+    let node = doc.node.as_ref()?;
+
+    let symbols = node
+        .children()
+        .filter_map(syntax_nodes::Component::new)
+        .filter_map(|component| component_symbol(&component, &line_index))
+        .collect();
+    Some(DocumentSymbolResponse::Nested(symbols))
+}
+
+pub fn workspace_symbols(
+    document_cache: &mut DocumentCache,
+    params: &WorkspaceSymbolParams,
+) -> Option<WorkspaceSymbolResponse> {
+    let query = params.query.to_lowercase();
+    let mut symbols = Vec::new();
+    for (path, doc) in document_cache.documents.all_documents() {
+        let node = match doc.node.as_ref() {
+            Some(node) => node,
+            None => continue,
+        };
+        let uri = match Url::from_file_path(path) {
+            Ok(uri) => uri,
+            Err(_) => continue,
+        };
+        let line_index = document_cache
+            .line_indices
+            .get(&uri)
+            .cloned()
+            .unwrap_or_else(|| LineIndex::new(&node.text().to_string()));
+        for component in node.children().filter_map(syntax_nodes::Component::new) {
+            let name = match identifier_text(&component.DeclaredIdentifier()) {
+                Some(name) => name,
+                None => continue,
+            };
+            if !query.is_empty() && !name.to_lowercase().contains(&query) {
+                continue;
+            }
+            let range = to_lsp_range(&line_index, &component.DeclaredIdentifier());
+            symbols.push(SymbolInformation {
+                name,
+                kind: SymbolKind::Class,
+                tags: None,
+                deprecated: None,
+                location: Location::new(uri.clone(), range),
+                container_name: None,
+            });
+        }
+    }
+    Some(WorkspaceSymbolResponse::Flat(symbols))
+}
+
+fn component_symbol(
+    component: &syntax_nodes::Component,
+    line_index: &LineIndex,
+) -> Option<DocumentSymbol> {
+    let name = identifier_text(&component.DeclaredIdentifier())?;
+    // `component.node`'s only `Element` child is the component's own root element (its base type
+    // plus `{ ... }` body) - recurse straight into its members rather than through
+    // `component_members`, which would otherwise see that `Element` has a `QualifiedName` (the
+    // base type, e.g. `Rectangle`) and treat it like any other named nested element, adding a
+    // spurious extra layer between the component and its actual members.
+    let root_element = component.node.children().find(|child| child.kind() == SyntaxKind::Element);
+    let children =
+        root_element.map(|element| component_members(&element, line_index)).unwrap_or_default();
+    Some(new_document_symbol(
+        name,
+        SymbolKind::Class,
+        to_lsp_range(line_index, &component.node),
+        to_lsp_range(line_index, &component.DeclaredIdentifier()),
+        Some(children),
+    ))
+}
+
+/// Collects the properties, callbacks, and nested element ids declared directly or transitively
+/// under `node` into document symbol children. A nested element without an id is named by its
+/// base type instead.
+fn component_members(node: &SyntaxNode, line_index: &LineIndex) -> Vec<DocumentSymbol> {
+    let mut members = Vec::new();
+    for child in node.children() {
+        match child.kind() {
+            SyntaxKind::PropertyDeclaration => {
+                if let Some(name) = child
+                    .child_node(SyntaxKind::DeclaredIdentifier)
+                    .and_then(|n| identifier_text(&n))
+                {
+                    members.push(new_document_symbol(
+                        name,
+                        SymbolKind::Property,
+                        to_lsp_range(line_index, &child),
+                        to_lsp_range(line_index, &child),
+                        None,
+                    ));
+                }
+            }
+            SyntaxKind::CallbackDeclaration => {
+                if let Some(name) = child
+                    .child_node(SyntaxKind::DeclaredIdentifier)
+                    .and_then(|n| identifier_text(&n))
+                {
+                    members.push(new_document_symbol(
+                        name,
+                        SymbolKind::Method,
+                        to_lsp_range(line_index, &child),
+                        to_lsp_range(line_index, &child),
+                        None,
+                    ));
+                }
+            }
+            SyntaxKind::SubElement | SyntaxKind::Element => {
+                // A `SubElement` carries the optional id beside the `Element` it wraps.
+                let id = child
+                    .parent()
+                    .filter(|p| p.kind() == SyntaxKind::SubElement)
+                    .and_then(|p| p.child_node(SyntaxKind::DeclaredIdentifier))
+                    .and_then(|n| identifier_text(&n));
+                if let Some(name) = id.or_else(|| {
+                    child.child_node(SyntaxKind::QualifiedName).and_then(|n| identifier_text(&n))
+                }) {
+                    let nested = component_members(&child, line_index);
+                    members.push(new_document_symbol(
+                        name,
+                        SymbolKind::Object,
+                        to_lsp_range(line_index, &child),
+                        to_lsp_range(line_index, &child),
+                        if nested.is_empty() { None } else { Some(nested) },
+                    ));
+                } else {
+                    members.extend(component_members(&child, line_index));
+                }
+            }
+            _ => {}
+        }
+    }
+    members
+}
+
+fn to_lsp_range(line_index: &LineIndex, node: &SyntaxNode) -> lsp_types::Range {
+    let range = node.text_range();
+    lsp_types::Range::new(
+        line_index.offset_to_position(range.start().into()),
+        line_index.offset_to_position(range.end().into()),
+    )
+}
+
+#[allow(deprecated)] // `deprecated` field of `DocumentSymbol` has no replacement yet
+fn new_document_symbol(
+    name: String,
+    kind: SymbolKind,
+    range: lsp_types::Range,
+    selection_range: lsp_types::Range,
+    children: Option<Vec<DocumentSymbol>>,
+) -> DocumentSymbol {
+    DocumentSymbol {
+        name,
+        detail: None,
+        kind,
+        tags: None,
+        deprecated: None,
+        range,
+        selection_range,
+        children,
+    }
+}
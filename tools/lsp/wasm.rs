@@ -0,0 +1,81 @@
+/* LICENSE BEGIN
+    This file is part of the SixtyFPS Project -- https://sixtyfps.io
+    Copyright (c) 2020 Olivier Goffart <olivier.goffart@sixtyfps.io>
+    Copyright (c) 2020 Simon Hausmann <simon.hausmann@sixtyfps.io>
+
+    SPDX-License-Identifier: GPL-3.0-only
+    This file is also available under commercial licensing terms.
+    Please contact info@sixtyfps.io for more information.
+LICENSE END */
+
+//! The `wasm32` host/adapter boundary: JSON-RPC messages come in and go out as plain byte
+//! buffers over whatever `postMessage`-style channel the embedding page owns - there is no stdio
+//! to read here, and no `wasm-bindgen` dependency either, just the raw C-ABI exports a JS glue
+//! script marshals `postMessage` payloads through.
+
+#![cfg(target_arch = "wasm32")]
+
+use crate::server::Server;
+use lsp_server::Message;
+use std::sync::Once;
+
+static INIT: Once = Once::new();
+static mut SERVER: Option<Server> = None;
+
+/// Called once by the host after it has read the `initialize` request off its own transport -
+/// there's no `Connection::initialize` handshake to drive here, so the host hands us the already
+/// parsed `InitializeParams` directly.
+///
+/// # Safety
+/// Must be called exactly once, before any call to [`lsp_handle_message`], and never from more
+/// than one thread concurrently - the host page's JS glue is expected to serialize all calls
+/// into this module.
+#[no_mangle]
+pub unsafe extern "C" fn lsp_initialize(ptr: *const u8, len: usize) {
+    let bytes = std::slice::from_raw_parts(ptr, len);
+    let init_param: lsp_types::InitializeParams =
+        serde_json::from_slice(bytes).expect("invalid InitializeParams JSON");
+
+    let mut compiler_config = sixtyfps_compilerlib::CompilerConfiguration::new(
+        sixtyfps_compilerlib::generator::OutputFormat::Interpreter,
+    );
+    compiler_config.style = Some("ugly".into());
+    let compiler_config: &'static _ = Box::leak(Box::new(compiler_config));
+
+    INIT.call_once(|| {
+        SERVER = Some(Server::new(init_param, compiler_config));
+    });
+}
+
+/// Feeds one serialized `lsp_server::Message` in, returns a serialized `Vec<Message>` of the
+/// responses/notifications the host should deliver back to the editor (writing its length to
+/// `out_len`). The returned buffer is leaked into wasm linear memory as a boxed slice - exactly
+/// `out_len` bytes, no spare capacity - so the host only ever needs to give `lsp_free` back the
+/// pointer and that same length. The host must pass it back to [`lsp_free`] once it has copied
+/// the bytes out.
+///
+/// # Safety
+/// `ptr`/`len` must describe a valid, readable byte slice, and [`lsp_initialize`] must already
+/// have run.
+#[no_mangle]
+pub unsafe extern "C" fn lsp_handle_message(ptr: *const u8, len: usize, out_len: *mut usize) -> *mut u8 {
+    let bytes = std::slice::from_raw_parts(ptr, len);
+    let msg: Message = serde_json::from_slice(bytes).expect("invalid Message JSON");
+    #[allow(static_mut_refs)]
+    let server = SERVER.as_ref().expect("lsp_initialize must be called first");
+    let responses = server.handle_message(msg);
+
+    let json = serde_json::to_vec(&responses).expect("Message is always serializable").into_boxed_slice();
+    *out_len = json.len();
+    Box::into_raw(json) as *mut u8
+}
+
+/// Releases a buffer previously returned by [`lsp_handle_message`].
+///
+/// # Safety
+/// `ptr`/`len` must be exactly the pointer and `out_len` value `lsp_handle_message` wrote out,
+/// and this must be called at most once per buffer.
+#[no_mangle]
+pub unsafe extern "C" fn lsp_free(ptr: *mut u8, len: usize) {
+    drop(Box::from_raw(std::slice::from_raw_parts_mut(ptr, len)));
+}